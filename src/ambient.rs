@@ -0,0 +1,168 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+/// A lux -> target-brightness-percent mapping, linearly interpolated between the
+/// configured points and clamped to the first/last value outside their range.
+#[derive(Debug, Clone)]
+pub struct Curve(Vec<(f64, f64)>);
+
+impl Curve {
+    pub fn new(mut points: Vec<(f64, f64)>) -> Curve {
+        // TOML's float grammar accepts `nan`, which `partial_cmp` can't order; fall back to
+        // treating it as equal rather than unwrapping into a panic on a malformed config.
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        Curve(points)
+    }
+
+    /// Map a lux reading to a brightness percentage via linear interpolation.
+    pub fn sample(&self, lux: f64) -> f64 {
+        let points = &self.0;
+        let first = match points.first() {
+            Some(first) => first,
+            None => return 100.0,
+        };
+        let last = points[points.len() - 1];
+
+        if lux <= first.0 {
+            return first.1;
+        }
+        if lux >= last.0 {
+            return last.1;
+        }
+
+        for window in points.windows(2) {
+            let (lux_a, percent_a) = window[0];
+            let (lux_b, percent_b) = window[1];
+            if lux >= lux_a && lux <= lux_b {
+                // Duplicate x points would otherwise divide by zero (0.0 / 0.0 = NaN); treat
+                // the segment as a step and take the first point's value.
+                if lux_b == lux_a {
+                    return percent_a;
+                }
+                let t = (lux - lux_a) / (lux_b - lux_a);
+                return percent_a + t * (percent_b - percent_a);
+            }
+        }
+
+        last.1
+    }
+}
+
+/// An ambient light sensor exposed under the IIO subsystem.
+pub struct AmbientLightSensor {
+    path: PathBuf,
+}
+
+impl AmbientLightSensor {
+    /// Look for the first `/sys/bus/iio/devices/iio:deviceN/in_illuminance_{raw,input}`.
+    pub fn find() -> Option<Self> {
+        let entries = fs::read_dir("/sys/bus/iio/devices").ok()?;
+
+        for entry in entries.flatten() {
+            for attr in ["in_illuminance_raw", "in_illuminance_input"] {
+                let path = entry.path().join(attr);
+                if path.exists() {
+                    debug!("found ambient light sensor at {}", path.display());
+                    return Some(AmbientLightSensor { path });
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read_lux(&self) -> Option<f64> {
+        fs::read_to_string(&self.path).ok()?.trim().parse().ok()
+    }
+}
+
+/// Exponential moving average low-pass filter, so a momentary flicker in ambient light
+/// doesn't cause visible pulsing in the panel's brightness.
+pub struct Smoother {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Smoother {
+    pub fn new(alpha: f64) -> Self {
+        Smoother {
+            alpha: alpha.clamp(0.0, 1.0),
+            value: None,
+        }
+    }
+
+    pub fn push(&mut self, sample: f64) -> f64 {
+        let smoothed = match self.value {
+            Some(previous) => previous + self.alpha * (sample - previous),
+            None => sample,
+        };
+        self.value = Some(smoothed);
+        smoothed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_clamps_outside_configured_range() {
+        let curve = Curve::new(vec![(10.0, 20.0), (100.0, 80.0)]);
+        assert_eq!(curve.sample(0.0), 20.0);
+        assert_eq!(curve.sample(1_000.0), 80.0);
+    }
+
+    #[test]
+    fn curve_interpolates_linearly_between_points() {
+        let curve = Curve::new(vec![(0.0, 0.0), (100.0, 100.0)]);
+        assert_eq!(curve.sample(50.0), 50.0);
+    }
+
+    #[test]
+    fn curve_sorts_unsorted_input_points() {
+        let curve = Curve::new(vec![(100.0, 100.0), (0.0, 0.0)]);
+        assert_eq!(curve.sample(50.0), 50.0);
+    }
+
+    #[test]
+    fn curve_empty_defaults_to_full_brightness() {
+        let curve = Curve::new(vec![]);
+        assert_eq!(curve.sample(42.0), 100.0);
+    }
+
+    #[test]
+    fn curve_duplicate_x_points_do_not_divide_by_zero() {
+        let curve = Curve::new(vec![(10.0, 20.0), (10.0, 40.0), (100.0, 80.0)]);
+        let sampled = curve.sample(10.0);
+        assert!(!sampled.is_nan());
+        assert_eq!(sampled, 20.0);
+    }
+
+    #[test]
+    fn curve_nan_point_does_not_panic_while_sorting() {
+        // TOML accepts `nan` as a float literal, so a malformed config can produce one here;
+        // constructing the curve must not panic even though NaN has no total order.
+        let curve = Curve::new(vec![(f64::NAN, 50.0), (10.0, 80.0)]);
+        assert!(!curve.sample(10.0).is_nan());
+    }
+
+    #[test]
+    fn smoother_returns_first_sample_unchanged() {
+        let mut smoother = Smoother::new(0.2);
+        assert_eq!(smoother.push(100.0), 100.0);
+    }
+
+    #[test]
+    fn smoother_blends_toward_new_samples_by_alpha() {
+        let mut smoother = Smoother::new(0.5);
+        smoother.push(0.0);
+        assert_eq!(smoother.push(100.0), 50.0);
+    }
+}
@@ -0,0 +1,251 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, warn};
+use serde::Deserialize;
+
+use crate::gamma::GammaCurve;
+
+/// System-wide config, checked before the user override.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/system76-oled.toml";
+
+/// One `[[panel]]` entry: identifies a machine by DMI vendor/model strings and names the
+/// Xrandr/DRM output connector its OLED panel is attached to.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PanelEntry {
+    pub sys_vendor: String,
+    pub product_version: String,
+    pub output: String,
+}
+
+/// Per-channel gamma curve and brightness floor, as written in the optional `[gamma]`
+/// section. Any field left unset keeps its neutral default.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GammaConfig {
+    pub red: Option<f64>,
+    pub green: Option<f64>,
+    pub blue: Option<f64>,
+    /// Brightness floor as a fraction of full brightness in `0.0..=1.0` (e.g. `0.2` for a
+    /// 20% floor), below which the panel is never dimmed. Values outside that range are
+    /// clamped, since a value like `20` (a common mistake when thinking in percent) would
+    /// otherwise pin the panel at full brightness.
+    pub min_brightness: Option<f64>,
+    /// Night-light color temperature in Kelvin (e.g. `4500`), applied on top of the gamma
+    /// curve above. An explicit override: takes precedence over the
+    /// `day_temperature`/`night_temperature` schedule below. Leave this and at least one of
+    /// the four schedule fields unset to keep the display's native white point.
+    pub temperature: Option<u32>,
+    /// Color temperature to use during the day, when the schedule below is configured.
+    /// Ignored if `temperature` is set.
+    pub day_temperature: Option<u32>,
+    /// Color temperature to use at night.
+    pub night_temperature: Option<u32>,
+    /// Local hour (`0..=23`) night starts, e.g. `20` for 8pm. The schedule only takes effect
+    /// once `day_temperature`, `night_temperature`, this, and `night_end_hour` are all set.
+    pub night_start_hour: Option<u32>,
+    /// Local hour (`0..=23`) night ends, e.g. `7` for 7am. May be less than
+    /// `night_start_hour`, in which case the night window wraps past midnight.
+    pub night_end_hour: Option<u32>,
+}
+
+impl GammaConfig {
+    /// The color temperature to apply right now: `temperature` if set, as an explicit
+    /// override; otherwise whichever side of the day/night schedule the current local hour
+    /// falls on, if the full schedule is configured; otherwise `None`, the native white point.
+    fn scheduled_temperature(&self) -> Option<u32> {
+        if self.temperature.is_some() {
+            return self.temperature;
+        }
+
+        let day = self.day_temperature?;
+        let night = self.night_temperature?;
+        let start = self.night_start_hour?;
+        let end = self.night_end_hour?;
+
+        let hour = local_hour();
+        let is_night = if start <= end {
+            hour >= start && hour < end
+        } else {
+            hour >= start || hour < end
+        };
+
+        Some(if is_night { night } else { day })
+    }
+
+    pub fn to_curve(&self) -> GammaCurve {
+        let defaults = GammaCurve::default();
+
+        let min_brightness = self.min_brightness.unwrap_or(defaults.min_brightness);
+        let min_brightness = if (0.0..=1.0).contains(&min_brightness) {
+            min_brightness
+        } else {
+            warn!(
+                "min_brightness {} is out of range 0.0..=1.0 (it's a fraction, not a percent); clamping",
+                min_brightness
+            );
+            min_brightness.clamp(0.0, 1.0)
+        };
+
+        GammaCurve {
+            red: self.red.unwrap_or(defaults.red),
+            green: self.green.unwrap_or(defaults.green),
+            blue: self.blue.unwrap_or(defaults.blue),
+            min_brightness,
+            temperature: self.scheduled_temperature(),
+        }
+    }
+}
+
+/// The local wall-clock hour (`0..=23`), used to decide day vs. night for the temperature
+/// schedule above.
+fn local_hour() -> u32 {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm.tm_hour as u32
+    }
+}
+
+/// How the ambient-light target is combined with the manual brightness-key target.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbientPolicy {
+    /// Use whichever target is brighter, so bumping the brightness keys always overrides
+    /// a dim sensor reading.
+    #[default]
+    Max,
+    /// Follow the sensor only.
+    AmbientOnly,
+    /// Ignore the sensor entirely.
+    ManualOnly,
+}
+
+/// Optional `[ambient_light]` section enabling auto-brightness from an IIO light sensor.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AmbientLightConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// `(lux, brightness_percent)` points, linearly interpolated and clamped at the ends.
+    #[serde(default = "AmbientLightConfig::default_curve")]
+    pub curve: Vec<(f64, f64)>,
+    /// Exponential-smoothing factor in `[0, 1]` applied to raw lux readings; lower is
+    /// slower to react but less prone to flicker-induced pulsing.
+    #[serde(default = "AmbientLightConfig::default_smoothing")]
+    pub smoothing: f64,
+    #[serde(default)]
+    pub policy: AmbientPolicy,
+}
+
+impl AmbientLightConfig {
+    fn default_curve() -> Vec<(f64, f64)> {
+        vec![
+            (0.0, 10.0),
+            (10.0, 20.0),
+            (100.0, 50.0),
+            (1_000.0, 80.0),
+            (10_000.0, 100.0),
+        ]
+    }
+
+    fn default_smoothing() -> f64 {
+        0.2
+    }
+}
+
+impl Default for AmbientLightConfig {
+    fn default() -> Self {
+        AmbientLightConfig {
+            enabled: false,
+            curve: Self::default_curve(),
+            smoothing: Self::default_smoothing(),
+            policy: AmbientPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Config {
+    #[serde(rename = "panel", default)]
+    pub panels: Vec<PanelEntry>,
+    pub gamma: Option<GammaConfig>,
+    pub ambient_light: Option<AmbientLightConfig>,
+}
+
+impl Config {
+    /// Load `/etc/system76-oled.toml`, then layer the user's `~/.config/system76-oled.toml`
+    /// on top: panel entries from both are combined, and a `[gamma]` section in the user
+    /// config overrides the system one wholesale.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        if let Some(system) = Self::read(Path::new(SYSTEM_CONFIG_PATH)) {
+            config.merge(system);
+        }
+
+        if let Some(path) = user_config_path() {
+            if let Some(user) = Self::read(&path) {
+                config.merge(user);
+            }
+        }
+
+        config
+    }
+
+    fn read(path: &Path) -> Option<Config> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                debug!("no config at {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        match toml::from_str(&text) {
+            Ok(config) => {
+                debug!("loaded config from {}", path.display());
+                Some(config)
+            }
+            Err(err) => {
+                error!("failed to parse {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Config) {
+        self.panels.extend(other.panels);
+        if other.gamma.is_some() {
+            self.gamma = other.gamma;
+        }
+        if other.ambient_light.is_some() {
+            self.ambient_light = other.ambient_light;
+        }
+    }
+
+    /// Find the output connector configured for this machine's DMI vendor/model, if any.
+    pub fn output_for(&self, vendor: &str, model: &str) -> Option<String> {
+        self.panels.iter()
+            .find(|panel| panel.sys_vendor == vendor && panel.product_version == model)
+            .map(|panel| panel.output.clone())
+    }
+
+    /// The gamma curve to apply, falling back to the neutral default when unconfigured.
+    pub fn gamma_curve(&self) -> GammaCurve {
+        self.gamma.as_ref()
+            .map(GammaConfig::to_curve)
+            .unwrap_or_default()
+    }
+
+    /// The ambient-light auto-brightness settings, falling back to (disabled) defaults.
+    pub fn ambient_light(&self) -> AmbientLightConfig {
+        self.ambient_light.clone().unwrap_or_default()
+    }
+}
+
+/// `~/.config/system76-oled.toml`, or `None` if `$HOME` isn't set.
+pub fn user_config_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(Path::new(&home).join(".config/system76-oled.toml"))
+}
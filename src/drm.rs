@@ -0,0 +1,179 @@
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+use drm::control::{connector, crtc, Device as ControlDevice};
+use drm::Device;
+use log::{debug, error, trace};
+
+use crate::gamma::GammaBackend;
+
+/// Find the sysfs output name (e.g. `eDP-1`, `HDMI-A-1`) for a connector on `card_name`
+/// (e.g. `card0`), by matching `/sys/class/drm/<card_name>-*/connector_id` against the
+/// connector's raw DRM handle. This is the same naming Xrandr and `panel::detect_oled_connector`
+/// use, unlike the `{:?}` Debug output of `connector::Interface`, which is a Rust enum
+/// variant name (`EmbeddedDisplayPort`) and never matches it.
+fn sysfs_connector_name(card_name: &str, handle: connector::Handle) -> Option<String> {
+    let raw_id = u32::from(handle);
+    let prefix = format!("{}-", card_name);
+
+    for entry in std::fs::read_dir("/sys/class/drm").ok()?.flatten() {
+        let path = entry.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+
+        let connector_id = match std::fs::read_to_string(path.join("connector_id")) {
+            Ok(text) => match text.trim().parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+
+        if connector_id == raw_id {
+            return name.split_once('-').map(|(_, suffix)| suffix.to_string());
+        }
+    }
+
+    None
+}
+
+/// Minimal wrapper around an open DRM device node, just enough to satisfy the `drm` crate's
+/// `Device`/`control::Device` marker traits.
+struct Card(File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for Card {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Device for Card {}
+impl ControlDevice for Card {}
+
+/// Gamma backend that sets a CRTC's gamma LUT directly through `drmModeCrtcSetGamma`,
+/// bypassing Xrandr entirely. This is the only backend that works under Wayland
+/// compositors such as Mutter, which own the X11-less KMS CRTC themselves.
+pub struct DrmBackend {
+    card: Card,
+    connector: String,
+    crtc: crtc::Handle,
+}
+
+impl DrmBackend {
+    /// Scan `/dev/dri/card*` for a connected connector whose name starts with
+    /// `output_name`, and bind to the CRTC currently driving it.
+    pub fn new(output_name: &str) -> Option<Self> {
+        let entries = match std::fs::read_dir("/dev/dri") {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("failed to list /dev/dri: {}", err);
+                return None;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if !name.starts_with("card") {
+                continue;
+            }
+
+            let file = match OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    debug!("failed to open {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let card = Card(file);
+
+            let resources = match card.resource_handles() {
+                Ok(resources) => resources,
+                Err(err) => {
+                    debug!("failed to get resources for {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            for handle in resources.connectors() {
+                let info = match card.get_connector(*handle, false) {
+                    Ok(info) => info,
+                    Err(_) => continue,
+                };
+
+                if info.state() != connector::State::Connected {
+                    continue;
+                }
+
+                let connector_name = match sysfs_connector_name(name, *handle) {
+                    Some(connector_name) => connector_name,
+                    None => continue,
+                };
+                trace!("connector {}", connector_name);
+                if !connector_name.starts_with(output_name) {
+                    continue;
+                }
+
+                let encoder_handle = match info.current_encoder() {
+                    Some(handle) => handle,
+                    None => continue,
+                };
+                let encoder = match card.get_encoder(encoder_handle) {
+                    Ok(encoder) => encoder,
+                    Err(_) => continue,
+                };
+                let crtc = match encoder.crtc() {
+                    Some(crtc) => crtc,
+                    None => continue,
+                };
+
+                return Some(DrmBackend {
+                    card,
+                    connector: connector_name,
+                    crtc,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl GammaBackend for DrmBackend {
+    fn gamma_size(&self, connector: &str) -> Option<usize> {
+        if connector != self.connector {
+            return None;
+        }
+
+        self.card.get_crtc(self.crtc).ok()
+            .map(|info| info.gamma_length() as usize)
+    }
+
+    fn set_gamma(&mut self, connector: &str, red: &[u16], green: &[u16], blue: &[u16]) -> bool {
+        if connector != self.connector {
+            return false;
+        }
+
+        match self.card.set_gamma(self.crtc, red, green, blue) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("failed to set DRM gamma: {}", err);
+                false
+            }
+        }
+    }
+}
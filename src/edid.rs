@@ -0,0 +1,149 @@
+/// The fixed 8-byte header every valid EDID block starts with.
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+/// Offset and length of each of the four 18-byte descriptor blocks in the base EDID block.
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+
+/// Descriptor tag marking a "Display Product Name" text descriptor.
+const DISPLAY_PRODUCT_NAME_TAG: u8 = 0xfc;
+
+/// The handful of fields from a base (128-byte) EDID block needed to identify a panel:
+/// the PNP manufacturer ID, product code, and product name string, if present.
+pub struct Edid {
+    manufacturer: [u8; 3],
+    product_code: u16,
+    product_name: Option<String>,
+}
+
+impl Edid {
+    /// Parse a base EDID block, returning `None` if it's too short or doesn't start with
+    /// the standard header.
+    pub fn parse(data: &[u8]) -> Option<Edid> {
+        if data.len() < 128 || data[0..8] != HEADER {
+            return None;
+        }
+
+        let id = u16::from_be_bytes([data[8], data[9]]);
+        // A well-formed EDID never has a zero 5-bit field here, but a corrupt one (from a
+        // misbehaving external monitor, say) might; use wrapping arithmetic throughout so
+        // that degrades to a garbage manufacturer string instead of an overflow panic.
+        let letter = |shift: u16| -> u8 {
+            b'A'.wrapping_add(((id >> shift) & 0x1f) as u8).wrapping_sub(1)
+        };
+        let manufacturer = [letter(10), letter(5), letter(0)];
+
+        let product_code = u16::from_le_bytes([data[10], data[11]]);
+
+        let product_name = DESCRIPTOR_OFFSETS.iter()
+            .find_map(|&offset| parse_text_descriptor(&data[offset..offset + 18], DISPLAY_PRODUCT_NAME_TAG));
+
+        Some(Edid {
+            manufacturer,
+            product_code,
+            product_name,
+        })
+    }
+
+    /// The three-letter PNP manufacturer ID, e.g. `"SDC"` for Samsung Display. Falls back
+    /// to `"???"` for a corrupt EDID whose decoded bytes aren't valid UTF-8.
+    pub fn manufacturer_id(&self) -> &str {
+        std::str::from_utf8(&self.manufacturer).unwrap_or("???")
+    }
+
+    pub fn product_code(&self) -> u16 {
+        self.product_code
+    }
+
+    /// Best-effort heuristic for "this is probably an OLED panel", for EDIDs that embed it
+    /// in their product name (several shipped System76 panels do, e.g. `"...OLED..."`).
+    pub fn looks_like_oled(&self) -> bool {
+        self.product_name.as_deref()
+            .map(|name| name.to_ascii_uppercase().contains("OLED"))
+            .unwrap_or(false)
+    }
+}
+
+/// A detailed-timing descriptor starts with a nonzero pixel clock; a display-descriptor
+/// instead starts with `00 00`, followed by a reserved byte, the tag, another reserved
+/// byte, and then up to 13 bytes of space-padded ASCII text.
+fn parse_text_descriptor(descriptor: &[u8], tag: u8) -> Option<String> {
+    if descriptor[0] != 0x00 || descriptor[1] != 0x00 || descriptor[3] != tag {
+        return None;
+    }
+
+    let text = &descriptor[5..18];
+    let end = text.iter().position(|&b| b == b'\n').unwrap_or(text.len());
+    Some(String::from_utf8_lossy(&text[..end]).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a 128-byte base EDID block with the given manufacturer word, product code,
+    /// and (optionally) a "Display Product Name" descriptor.
+    fn sample_edid(manufacturer_word: u16, product_code: u16, product_name: Option<&str>) -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data[0..8].copy_from_slice(&HEADER);
+        data[8..10].copy_from_slice(&manufacturer_word.to_be_bytes());
+        data[10..12].copy_from_slice(&product_code.to_le_bytes());
+
+        if let Some(name) = product_name {
+            let offset = DESCRIPTOR_OFFSETS[0];
+            data[offset] = 0x00;
+            data[offset + 1] = 0x00;
+            data[offset + 2] = 0x00;
+            data[offset + 3] = DISPLAY_PRODUCT_NAME_TAG;
+            data[offset + 4] = 0x00;
+
+            let text = name.as_bytes();
+            let len = text.len().min(13);
+            data[offset + 5..offset + 5 + len].copy_from_slice(&text[..len]);
+            if len < 13 {
+                data[offset + 5 + len] = b'\n';
+            }
+        }
+
+        data
+    }
+
+    fn manufacturer_word(letters: [u8; 3]) -> u16 {
+        let [a, b, c] = letters;
+        ((a as u16 - b'A' as u16 + 1) << 10)
+            | ((b as u16 - b'A' as u16 + 1) << 5)
+            | (c as u16 - b'A' as u16 + 1)
+    }
+
+    #[test]
+    fn parses_manufacturer_and_product_code() {
+        let data = sample_edid(manufacturer_word(*b"SDC"), 0x1234, None);
+        let edid = Edid::parse(&data).expect("valid EDID");
+        assert_eq!(edid.manufacturer_id(), "SDC");
+        assert_eq!(edid.product_code(), 0x1234);
+        assert!(!edid.looks_like_oled());
+    }
+
+    #[test]
+    fn detects_oled_in_product_name() {
+        let data = sample_edid(manufacturer_word(*b"ATN"), 0x4e41, Some("ATNA56 OLED"));
+        let edid = Edid::parse(&data).expect("valid EDID");
+        assert!(edid.looks_like_oled());
+    }
+
+    #[test]
+    fn rejects_short_or_bad_header() {
+        assert!(Edid::parse(&[0u8; 10]).is_none());
+
+        let mut data = sample_edid(manufacturer_word(*b"SDC"), 0, None);
+        data[0] = 0xff;
+        assert!(Edid::parse(&data).is_none());
+    }
+
+    #[test]
+    fn malformed_manufacturer_field_does_not_panic() {
+        // A zero 5-bit manufacturer field used to overflow `b'A' + 255` and panic here.
+        let data = sample_edid(0, 0, None);
+        let edid = Edid::parse(&data).expect("parses despite the zero field");
+        let _ = edid.manufacturer_id();
+    }
+}
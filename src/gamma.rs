@@ -0,0 +1,136 @@
+use std::os::unix::io::RawFd;
+
+use log::error;
+
+/// Abstracts over the mechanism used to push a gamma LUT to the display controller, so the
+/// daemon can drive either Xrandr (X11) or DRM (Wayland, and anywhere else without a usable
+/// X11 CRTC) through the same brightness logic.
+pub trait GammaBackend {
+    /// Number of entries in `connector`'s gamma ramp, if this backend currently knows of it.
+    fn gamma_size(&self, connector: &str) -> Option<usize>;
+
+    /// Replace `connector`'s gamma ramp with the given per-channel LUTs, sized for whatever
+    /// `gamma_size()` returned earlier. That size is a separate call and can go stale (an
+    /// output reconfiguration between the two), so implementations must not assume `red`,
+    /// `green`, and `blue` still match the ramp's current size — re-check and fail (`false`)
+    /// rather than panic if they don't.
+    fn set_gamma(&mut self, connector: &str, red: &[u16], green: &[u16], blue: &[u16]) -> bool;
+
+    /// File descriptor the run loop should poll for backend-specific change events, if any.
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Pump any pending backend events, returning `true` if a change should force a brightness
+    /// refresh (for example, an output was connected or reconfigured).
+    fn process_events(&mut self) -> bool {
+        false
+    }
+}
+
+/// Per-channel gamma exponents and a brightness floor, typically sourced from the on-disk
+/// config so users can tune color balance without recompiling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GammaCurve {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    /// Brightness floor as a fraction of full brightness in `0.0..=1.0`, not a percent.
+    pub min_brightness: f64,
+    /// Night-light color temperature in Kelvin. `None` leaves the white point untouched.
+    pub temperature: Option<u32>,
+}
+
+impl Default for GammaCurve {
+    fn default() -> Self {
+        GammaCurve {
+            red: 1.0,
+            green: 1.0,
+            blue: 1.0,
+            min_brightness: 0.0,
+            temperature: None,
+        }
+    }
+}
+
+/// Approximate the Planckian-locus white point for `temperature` Kelvin as per-channel
+/// scale factors in `[0, 1]`, using the same curve fit redshift/f.lux use.
+fn white_point_factors(temperature: u32) -> (f64, f64, f64) {
+    let t = temperature as f64 / 100.0;
+
+    let red = if t <= 66.0 {
+        1.0
+    } else {
+        (1.292 * (t - 60.0).powf(-0.1332)).clamp(0.0, 1.0)
+    };
+
+    let green = if t <= 66.0 {
+        (0.39 * t.ln() - 0.631).clamp(0.0, 1.0)
+    } else {
+        (1.1298 * (t - 60.0).powf(-0.0755)).clamp(0.0, 1.0)
+    };
+
+    let blue = if t >= 66.0 {
+        1.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        (0.543 * (t - 10.0).ln() - 1.196).clamp(0.0, 1.0)
+    };
+
+    (red, green, blue)
+}
+
+/// Compute and push a brightness-scaled, white-point-tinted gamma ramp for `connector`
+/// through `backend`.
+pub fn apply_brightness(backend: &mut dyn GammaBackend, connector: &str, brightness_opt: Option<f64>, curve: &GammaCurve) {
+    let size = match backend.gamma_size(connector) {
+        Some(size) => size,
+        None => {
+            error!("failed to get gamma ramp size for {}", connector);
+            return;
+        }
+    };
+
+    // Never let the config-provided floor dim the panel below min_brightness.
+    let brightness_opt = brightness_opt.map(|brightness| brightness.max(curve.min_brightness));
+
+    let (red_factor, green_factor, blue_factor) = curve.temperature
+        .map(white_point_factors)
+        .unwrap_or((1.0, 1.0, 1.0));
+
+    let mut red = vec![0u16; size];
+    let mut green = vec![0u16; size];
+    let mut blue = vec![0u16; size];
+
+    for i in 0..size {
+        let calculate_value = |gamma_opt: Option<f64>, factor: f64| -> u16 {
+            // Calculate standard gamma value
+            let mut value = (i as f64) / ((size - 1) as f64);
+
+            // Apply gamma for channel
+            if let Some(gamma) = gamma_opt {
+                value = value.powf(1.0 / gamma);
+            }
+
+            // Apply brightness
+            if let Some(brightness) = brightness_opt {
+                value *= brightness;
+            }
+
+            // Apply night-light white point
+            value *= factor;
+
+            // Convert to short
+            (value.min(1.0) * 65535.0) as u16
+        };
+
+        red[i] = calculate_value(Some(curve.red), red_factor);
+        green[i] = calculate_value(Some(curve.green), green_factor);
+        blue[i] = calculate_value(Some(curve.blue), blue_factor);
+    }
+
+    if !backend.set_gamma(connector, &red, &green, &blue) {
+        error!("failed to set gamma for {}", connector);
+    }
+}
@@ -1,282 +1,57 @@
+mod ambient;
+mod config;
+mod drm;
+mod edid;
+mod gamma;
+mod panel;
+mod runloop;
+mod xrandr;
+
 use env_logger::Env;
 use inotify::{
     Inotify,
+    WatchDescriptor,
     WatchMask,
 };
-use log::{debug, error, info, trace};
-use std::{fs, mem, process, ptr, slice, str};
+use log::{debug, info, trace};
+use std::{env, fs, process};
 use std::io::{Error, Read, Seek, SeekFrom};
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::ptr::NonNull;
-use x11::{xlib, xrandr};
-
-pub struct ScreenNumber(libc::c_int);
-
-pub struct RootWindow(libc::c_ulong);
-
-pub struct Crtc(xrandr::RRCrtc);
-
-pub struct CrtcGamma(NonNull<xrandr::XRRCrtcGamma>);
-
-impl CrtcGamma {
-    pub fn size(&self) -> libc::c_int {
-        unsafe {
-            self.0.as_ref().size
-        }
-    }
-
-    pub fn channels(&mut self) -> (&mut [libc::c_ushort], &mut [libc::c_ushort], &mut [libc::c_ushort]) {
-        unsafe {
-            (
-                slice::from_raw_parts_mut(
-                    self.0.as_ref().red,
-                    self.0.as_ref().size as usize
-                ),
-                slice::from_raw_parts_mut(
-                    self.0.as_ref().green,
-                    self.0.as_ref().size as usize
-                ),
-                slice::from_raw_parts_mut(
-                    self.0.as_ref().blue,
-                    self.0.as_ref().size as usize
-                ),
-            )
-        }
-    }
-}
-
-impl Drop for CrtcGamma {
-    fn drop(&mut self) {
-        unsafe {
-            xrandr::XRRFreeGamma(self.0.as_ptr());
-        }
-    }
-}
-
-pub struct OutputInfo(NonNull<xrandr::XRROutputInfo>);
-
-impl OutputInfo {
-    pub fn name(&self) -> &[u8] {
-        unsafe {
-            slice::from_raw_parts(
-                self.0.as_ref().name as *const u8,
-                self.0.as_ref().nameLen as usize
-            )
-        }
-    }
-
-    pub fn crtc(&self) -> Option<Crtc> {
-        let crtc = unsafe {
-            self.0.as_ref().crtc
-        };
-        if crtc == 0 {
-            None
-        } else {
-            Some(Crtc(crtc))
-        }
-    }
-}
-
-impl Drop for OutputInfo {
-    fn drop(&mut self) {
-        unsafe {
-            xrandr::XRRFreeOutputInfo(self.0.as_ptr());
-        }
-    }
-}
-
-pub struct Output(xrandr::RROutput);
-
-pub struct OutputsIter<'a> {
-    items: &'a [xrandr::RROutput],
-    i: usize,
-}
-
-impl<'a> Iterator for OutputsIter<'a> {
-    type Item = Output;
-    fn next(&mut self) -> Option<Output> {
-        if let Some(item) = self.items.get(self.i) {
-            self.i += 1;
-            Some(Output(*item))
-        } else {
-            None
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use ambient::{AmbientLightSensor, Curve, Smoother};
+use config::{AmbientPolicy, Config};
+use drm::DrmBackend;
+use gamma::GammaBackend;
+use runloop::RunLoop;
+use xrandr::XrandrBackend;
+
+/// How often to re-evaluate `[gamma]`'s day/night temperature schedule while otherwise
+/// idle, so an hour boundary is crossed automatically instead of only on the next config
+/// file edit or brightness-key press.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Pick the gamma backend for the current session: Xrandr outside a Wayland session when
+/// an X11 display is reachable; otherwise (any Wayland session, even with an X11 display
+/// available, or no usable X11 display) driving the DRM CRTC directly.
+fn select_backend(output: &str) -> Box<dyn GammaBackend> {
+    let wayland_session = env::var_os("WAYLAND_DISPLAY").is_some()
+        || env::var("XDG_SESSION_TYPE").map(|value| value == "wayland").unwrap_or(false);
+
+    if !wayland_session {
+        if let Some(backend) = XrandrBackend::new() {
+            info!("using Xrandr gamma backend");
+            return Box::new(backend);
         }
+        debug!("no usable X11 display, falling back to DRM gamma backend");
     }
-}
-
-pub struct ScreenResources(NonNull<xrandr::XRRScreenResources>);
 
-impl ScreenResources {
-    pub fn outputs(&self) -> OutputsIter {
-        let items = unsafe {
-            slice::from_raw_parts(
-                self.0.as_ref().outputs,
-                self.0.as_ref().noutput as usize
-            )
-        };
-        OutputsIter {
-            items,
-            i: 0,
-        }
+    if let Some(backend) = DrmBackend::new(output) {
+        info!("using DRM gamma backend");
+        return Box::new(backend);
     }
-}
 
-impl Drop for ScreenResources {
-    fn drop(&mut self) {
-        unsafe {
-            xrandr::XRRFreeScreenResources(self.0.as_ptr());
-        }
-    }
-}
-
-pub struct Display(NonNull<xlib::Display>);
-
-impl Display {
-    pub fn new() -> Option<Self> {
-        NonNull::new(unsafe {
-            xlib::XOpenDisplay(ptr::null())
-        }).map(Self)
-    }
-
-    pub fn default_screen_number(&self) -> ScreenNumber {
-        ScreenNumber(unsafe {
-            xlib::XDefaultScreen(self.0.as_ptr())
-        })
-    }
-
-    pub fn root_window(&self, screen_number: &ScreenNumber) -> RootWindow {
-        RootWindow(unsafe {
-            xlib::XRootWindow(self.0.as_ptr(), screen_number.0)
-        })
-    }
-
-    pub fn get_screen_resources(&self, root_window: &RootWindow, current: bool) -> Option<ScreenResources> {
-        NonNull::new(unsafe {
-            if current {
-                xrandr::XRRGetScreenResourcesCurrent(self.0.as_ptr(), root_window.0)
-            } else {
-                xrandr::XRRGetScreenResources(self.0.as_ptr(), root_window.0)
-            }
-        }).map(ScreenResources)
-    }
-
-    pub fn get_output_info(&self, resources: &ScreenResources, output: &Output) -> Option<OutputInfo> {
-        NonNull::new(unsafe {
-            xrandr::XRRGetOutputInfo(self.0.as_ptr(), resources.0.as_ptr(), output.0)
-        }).map(OutputInfo)
-    }
-
-    pub fn get_crtc_gamma(&self, crtc: &Crtc) -> Option<CrtcGamma> {
-        NonNull::new(unsafe {
-            xrandr::XRRGetCrtcGamma(self.0.as_ptr(), crtc.0)
-        }).map(CrtcGamma)
-    }
-
-    pub fn set_crtc_gamma(&mut self, crtc: &Crtc, gamma: &CrtcGamma) {
-        unsafe {
-            xrandr::XRRSetCrtcGamma(self.0.as_ptr(), crtc.0, gamma.0.as_ptr());
-        }
-    }
-
-    pub fn select_input(&mut self, root_window: &RootWindow, mask: libc::c_int) {
-        unsafe {
-            xrandr::XRRSelectInput(self.0.as_ptr(), root_window.0, mask);
-        }
-    }
-
-    pub fn flush(&mut self) {
-        unsafe {
-            xlib::XFlush(self.0.as_ptr());
-        }
-    }
-
-    pub fn pending(&self) -> libc::c_int {
-        unsafe {
-            xlib::XPending(self.0.as_ptr())
-        }
-    }
-}
-
-impl AsRawFd for Display {
-    fn as_raw_fd(&self) -> RawFd {
-        unsafe {
-            xlib::XConnectionNumber(self.0.as_ptr())
-        }
-    }
-}
-
-impl Drop for Display {
-    fn drop(&mut self) {
-        unsafe {
-            xlib::XCloseDisplay(self.0.as_ptr());
-        }
-    }
-}
-
-fn xrandr_output_brightness(display: &mut Display, root_window: &RootWindow, output_name: &str, brightness_opt: Option<f64>) {
-    if let Some(resources) = display.get_screen_resources(&root_window, true) {
-        trace!("resources {:p}", resources.0.as_ptr());
-        for output in resources.outputs() {
-            trace!("output {:#x}", output.0);
-            if let Some(info) = display.get_output_info(&resources, &output) {
-                trace!("info {:p}", info.0.as_ptr());
-                if let Ok(name) = str::from_utf8(info.name()) {
-                    trace!("name {}", name);
-                    if name.starts_with(output_name) {
-                        trace!("matches {}", output_name);
-                        if let Some(crtc) = info.crtc() {
-                            trace!("crtc {:#x}", crtc.0);
-                            if let Some(mut gamma) = display.get_crtc_gamma(&crtc) {
-                                trace!("gamma {:p}", gamma.0.as_ptr());
-
-                                let size = gamma.size() as usize;
-                                let (red, green, blue) = gamma.channels();
-                                for i in 0..size {
-                                    let r = &mut red[i];
-                                    let g = &mut green[i];
-                                    let b = &mut blue[i];
-
-                                    let calulate_value = |gamma_opt: Option<f64>| -> u16 {
-                                        // Calculate standard gamma value
-                                        let mut value = (i as f64) / ((size - 1) as f64);
-
-                                        // Apply gamma for channel
-                                        if let Some(gamma) = gamma_opt {
-                                            value = value.powf(1.0 / gamma);
-                                        }
-
-                                        // Apply brightness
-                                        if let Some(brightness) = brightness_opt {
-                                            value *= brightness;
-                                        }
-
-                                        // Convert to short
-                                        (value.min(1.0) * 65535.0) as u16
-                                    };
-
-                                    *r = calulate_value(None);
-                                    *g = calulate_value(None);
-                                    *b = calulate_value(None);
-                                }
-
-                                trace!("set gamma");
-                                display.set_crtc_gamma(&crtc, &gamma);
-
-                                trace!("flush");
-                                display.flush();
-                            } else {
-                                error!("failed to get X gamma info");
-                            }
-                        }
-                    }
-                }
-            } else {
-                error!("failed to get X output info");
-            }
-        }
-    } else {
-        error!("failed to get X screen resources");
-    }
+    panic!("failed to initialize a gamma backend for '{}'", output);
 }
 
 fn main() {
@@ -289,18 +64,27 @@ fn main() {
         .unwrap_or(String::new());
     let model = model.trim();
 
-    let output_opt = match (vendor, model) {
-        ("System76", "addw1") => Some("eDP-1"),
-        _ => None,
-    };
+    let mut config = Config::load();
+
+    let output_opt = panel::detect_oled_connector()
+        .or_else(|| config.output_for(vendor, model))
+        .or_else(|| {
+            match (vendor, model) {
+                ("System76", "addw1") => Some("eDP-1".to_string()),
+                _ => None,
+            }
+        });
 
     let output = if let Some(output) = output_opt {
-        info!("Vendor '{}' Model '{}' has OLED display on '{}'", vendor, model, output);
+        info!("OLED display detected on '{}' (vendor '{}' model '{}')", output, vendor, model);
         output
     } else {
         debug!("Vendor '{}' Model '{}' does not have OLED display", vendor, model);
         process::exit(0);
     };
+    let output = output.as_str();
+
+    let mut gamma_curve = config.gamma_curve();
 
     let mut inotify = Inotify::init()
         .expect("failed to initialize inotify");
@@ -321,23 +105,38 @@ fn main() {
     let mut max_file = fs::File::open(max_path)
         .expect("failed to open max brightness");
 
-    let mut display = Display::new().expect("failed to open X display");
-    trace!("display {:p}", display.0.as_ptr());
-
-    let mut xrr_event_base = 0;
-    let mut xrr_error_base = 0;
-    if unsafe { xrandr::XRRQueryExtension(display.0.as_ptr(), &mut xrr_event_base, &mut xrr_error_base) } == 0 {
-        panic!("Xrandr extension not found");
+    // Watch whichever config files exist so `[gamma]` and `[ambient_light]` edits apply
+    // without a restart; the panel output and the light sensor are chosen once, at startup.
+    let mut config_watches: Vec<WatchDescriptor> = Vec::new();
+    for path in [config::SYSTEM_CONFIG_PATH.to_string()]
+        .into_iter()
+        .chain(config::user_config_path().map(|path| path.to_string_lossy().into_owned()))
+    {
+        match inotify.add_watch(&path, WatchMask::MODIFY) {
+            Ok(watch) => config_watches.push(watch),
+            Err(err) => debug!("not watching {}: {}", path, err),
+        }
     }
-    trace!("xrr_event_base {:#x}, xrr_error_base {:#x}", xrr_event_base, xrr_error_base);
 
-    let screen_number = display.default_screen_number();
-    trace!("screen_number {:#x}", screen_number.0);
+    let mut ambient_config = config.ambient_light();
+    let ambient_sensor = if ambient_config.enabled {
+        AmbientLightSensor::find()
+    } else {
+        None
+    };
+
+    let ambient_watch = ambient_sensor.as_ref().and_then(|sensor| {
+        inotify.add_watch(sensor.path(), WatchMask::MODIFY)
+            .map_err(|err| debug!("not watching {}: {}", sensor.path().display(), err))
+            .ok()
+    });
 
-    let root_window = display.root_window(&screen_number);
-    trace!("root_window {:#x}", root_window.0);
+    let mut ambient_curve = Curve::new(ambient_config.curve.clone());
+    let mut ambient_smoother = Smoother::new(ambient_config.smoothing);
+    let mut ambient_update = ambient_sensor.is_some();
+    let mut ambient_target = 100.0;
 
-    display.select_input(&root_window, xrandr::RROutputChangeNotifyMask);
+    let mut backend = select_backend(output);
 
     let dbus_system = dbus::Connection::get_private(dbus::BusType::System)
         .expect("failed to connect to D-Bus system bus");
@@ -357,12 +156,17 @@ fn main() {
         fd: inotify.as_raw_fd(),
         events: libc::POLLIN,
         revents: 0,
-    }, libc::pollfd {
-        fd: display.as_raw_fd(),
-        events: libc::POLLIN,
-        revents: 0,
     }];
 
+    let backend_pollfd = backend.as_raw_fd().map(|fd| {
+        pollfds.push(libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        pollfds.len() - 1
+    });
+
     let dbus_system_pollfd = pollfds.len();
     for watch in dbus_system.watch_fds() {
         pollfds.push(watch.to_pollfd());
@@ -379,11 +183,20 @@ fn main() {
     let mut max_update = true;
     let mut max_str = String::with_capacity(256);
     let mut max = 0;
-    let mut current = !0;
-
-    let mut timeout = -1;
-    let mut timeout_times = 0;
+    let mut run_loop = RunLoop::new();
+    let mut next_schedule_check = Instant::now();
     loop {
+        if Instant::now() >= next_schedule_check {
+            next_schedule_check = Instant::now() + SCHEDULE_CHECK_INTERVAL;
+
+            let scheduled_temperature = config.gamma_curve().temperature;
+            if scheduled_temperature != gamma_curve.temperature {
+                info!("night-light schedule changed color temperature to {:?}K", scheduled_temperature);
+                gamma_curve.temperature = scheduled_temperature;
+                run_loop.invalidate();
+            }
+        }
+
         if requested_update {
             requested_str.clear();
             requested_file.seek(SeekFrom::Start(0))
@@ -408,49 +221,57 @@ fn main() {
             debug!("max {}", max);
         }
 
-        let next = requested * 100 / max;
-        debug!("next {}%", next);
-        while current != next {
-            current = next;
-            /* Smooth transition (may require use of xlib for performance)
-            if current == !0 {
-                current = next;
-            } else if current < next {
-                current += 1;
-            } else if current > next {
-                current -= 1;
+        if ambient_update {
+            if let Some(sensor) = &ambient_sensor {
+                if let Some(lux) = sensor.read_lux() {
+                    let smoothed = ambient_smoother.push(lux);
+                    ambient_target = ambient_curve.sample(smoothed);
+                    debug!("ambient {:.1} lux (smoothed {:.1}) -> {:.0}%", lux, smoothed, ambient_target);
+                }
             }
-            */
+            ambient_update = false;
+        }
+
+        let manual_next = requested * 100 / max;
+        let next = match (&ambient_sensor, ambient_config.policy) {
+            (Some(_), AmbientPolicy::AmbientOnly) => ambient_target.round() as u64,
+            (Some(_), AmbientPolicy::ManualOnly) | (None, _) => manual_next,
+            (Some(_), AmbientPolicy::Max) => manual_next.max(ambient_target.round() as u64),
+        };
+        debug!("next {}%", next);
+        run_loop.set_target(next);
+
+        if run_loop.step() {
+            let current = run_loop.current();
 
-            xrandr_output_brightness(&mut display, &root_window, output, if current == 100 {
+            gamma::apply_brightness(&mut *backend, output, if current == 100 {
                 None
             } else {
                 Some(current as f64 / 100.0)
-            });
+            }, &gamma_curve);
 
             debug!("current {}%", current);
         }
 
-        // Use poll to establish a timeout
+        // The sooner of the animator's next frame, the Mutter re-assertion backoff, and the
+        // next night-light schedule check, or block indefinitely if none is pending.
+        let schedule_timeout = next_schedule_check.saturating_duration_since(Instant::now())
+            .as_millis() as libc::c_int;
+        let poll_timeout = runloop::combine_timeout(run_loop.poll_timeout(), schedule_timeout);
         for pollfd in pollfds.iter_mut() {
             pollfd.revents = 0;
         }
-        trace!("poll fds: {}, timeout: {})", pollfds.len(), timeout);
+        trace!("poll fds: {}, timeout: {})", pollfds.len(), poll_timeout);
         let count = unsafe {
-            libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout)
+            libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, poll_timeout)
         };
-        trace!("poll fds: {} timeout: {} = {}", pollfds.len(), timeout, count);
+        trace!("poll fds: {} timeout: {} = {}", pollfds.len(), poll_timeout, count);
 
         if count < 0 {
             panic!("failed to poll: {}", Error::last_os_error());
         } else if count == 0 {
-            // Update from timeout
-            current = !0;
-            if timeout_times == 0 {
-                timeout = -1;
-            } else {
-                timeout_times -= 1;
-            }
+            // Mutter may still be fighting us for gamma control; force a reapply.
+            run_loop.on_poll_timeout();
         } else {
             if pollfds[0].revents > 0 {
                 let mut buffer = [0; 1024];
@@ -465,30 +286,24 @@ fn main() {
                     if event.wd == max_watch {
                         max_update = true;
                     }
+                    if Some(&event.wd) == ambient_watch.as_ref() {
+                        ambient_update = true;
+                    }
+                    if config_watches.contains(&event.wd) {
+                        info!("config file changed, reloading gamma curve");
+                        config = Config::load();
+                        gamma_curve = config.gamma_curve();
+                        ambient_config = config.ambient_light();
+                        ambient_curve = Curve::new(ambient_config.curve.clone());
+                        ambient_smoother = Smoother::new(ambient_config.smoothing);
+                        run_loop.invalidate();
+                    }
                 }
             }
 
-            if pollfds[1].revents > 0 {
-                while display.pending() > 0 {
-                    unsafe {
-                        let mut event = mem::zeroed::<xlib::XEvent>();
-                        xlib::XNextEvent(display.0.as_ptr(), &mut event);
-                        trace!("event {:#x}", event.type_);
-                        if event.type_ >= xrr_event_base {
-                            let xrr_event_type = event.type_ - xrr_event_base;
-                            trace!("xrr_event {:#x}", xrr_event_type);
-                            if xrr_event_type == xrandr::RRNotify {
-                                let notify_event: &xrandr::XRRNotifyEvent = event.as_ref();
-                                trace!("notify_event {:?}", notify_event);
-                                if notify_event.subtype == xrandr::RRNotify_OutputChange {
-                                    let output_change_event: &xrandr::XRROutputChangeNotifyEvent = event.as_ref();
-                                    trace!("output_change_event {:?}", output_change_event);
-                                }
-
-                                current = !0;
-                            }
-                        }
-                    }
+            if let Some(i) = backend_pollfd {
+                if pollfds[i].revents > 0 && backend.process_events() {
+                    run_loop.invalidate();
                 }
             }
 
@@ -497,12 +312,11 @@ fn main() {
                     for item in dbus_system.watch_handle(pollfd.fd, dbus::WatchEvent::from_revents(pollfd.revents)) {
                         trace!("dbus system item {:?}", item);
 
-                        // Mutter displays have changed, force a brightness update. A timeout is
-                        // used because the gamma changes shortly after receiving this signal
+                        // Mutter displays have changed, force a brightness update. A backoff
+                        // is used because the gamma changes shortly after receiving this signal
                         // TODO: Figure out how to avoid mutter setting gamma
-                        current = !0;
-                        timeout = 100;
-                        timeout_times = 10;
+                        run_loop.invalidate();
+                        run_loop.trigger_backoff(100, 10);
                     }
                 }
             }
@@ -512,12 +326,11 @@ fn main() {
                     for item in dbus_session.watch_handle(pollfd.fd, dbus::WatchEvent::from_revents(pollfd.revents)) {
                         trace!("dbus session item {:?}", item);
 
-                        // Mutter displays have changed, force a brightness update. A timeout is
-                        // used because the gamma changes shortly after receiving this signal
+                        // Mutter displays have changed, force a brightness update. A backoff
+                        // is used because the gamma changes shortly after receiving this signal
                         // TODO: Figure out how to avoid mutter setting gamma
-                        current = !0;
-                        timeout = 100;
-                        timeout_times = 10;
+                        run_loop.invalidate();
+                        run_loop.trigger_backoff(100, 10);
                     }
                 }
             }
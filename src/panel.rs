@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+
+use log::{debug, trace};
+
+use crate::edid::Edid;
+
+/// EDID (manufacturer ID, product code) pairs for OLED panels known to ship in System76
+/// laptops, checked before falling back to the `looks_like_oled` heuristic.
+const KNOWN_OLED_PANELS: &[(&str, u16)] = &[
+    ("SDC", 0x4154),
+    ("ATN", 0x4e41),
+];
+
+/// Scan `/sys/class/drm/*/edid` for a connected panel that is either in our known-OLED
+/// table or whose EDID looks like an OLED panel, returning its output name (e.g.
+/// `eDP-1`) in the form Xrandr and DRM both use elsewhere in this daemon.
+///
+/// Returns `None` if no `/sys/class/drm` connector has a readable, OLED-looking EDID,
+/// so callers can fall back to the DMI-based config/allowlist.
+pub fn detect_oled_connector() -> Option<String> {
+    let entries = fs::read_dir("/sys/class/drm").ok()?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let data = match fs::read(path.join("edid")) {
+            Ok(data) if !data.is_empty() => data,
+            _ => continue,
+        };
+
+        let edid = match Edid::parse(&data) {
+            Some(edid) => edid,
+            None => continue,
+        };
+        trace!("{}: manufacturer {} product {:#06x}", path.display(), edid.manufacturer_id(), edid.product_code());
+
+        let known = KNOWN_OLED_PANELS.iter()
+            .any(|&(manufacturer, product_code)| manufacturer == edid.manufacturer_id() && product_code == edid.product_code());
+
+        if known || edid.looks_like_oled() {
+            if let Some(output) = connector_name(&path) {
+                debug!("detected OLED panel on {}", output);
+                return Some(output);
+            }
+        }
+    }
+
+    None
+}
+
+/// `/sys/class/drm/card0-eDP-1` -> `eDP-1`.
+fn connector_name(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    file_name.split_once('-').map(|(_, output)| output.to_string())
+}
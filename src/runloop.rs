@@ -0,0 +1,282 @@
+use std::time::{Duration, Instant};
+
+/// Cadence at which `Animator` advances brightness while transitioning, chosen to look
+/// smooth on an OLED panel without generating more gamma writes than needed.
+pub const FRAME_DURATION: Duration = Duration::from_millis(12);
+
+/// Sentinel meaning "no displayed value yet, or invalidated" — the next `step()` applies
+/// the target immediately rather than easing toward it.
+const UNSET: u64 = !0;
+
+/// Steps a displayed brightness percentage toward a target at a fixed cadence, instead of
+/// snapping instantly, while still telling the run loop how long it can safely block.
+pub struct Animator {
+    current: u64,
+    target: u64,
+    last_step: Option<Instant>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Animator {
+            current: UNSET,
+            target: UNSET,
+            last_step: None,
+        }
+    }
+
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Set the brightness percentage to animate toward.
+    pub fn set_target(&mut self, target: u64) {
+        self.target = target;
+    }
+
+    /// Force the next `step()` to apply immediately rather than ease in, for cases where
+    /// the gamma ramp itself (not the target brightness) needs to be reasserted: an output
+    /// was reconfigured, or a compositor is suspected of clobbering our LUT.
+    pub fn invalidate(&mut self) {
+        self.current = UNSET;
+        self.last_step = None;
+    }
+
+    /// `true` while `current` has not yet caught up to `target`.
+    pub fn is_animating(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Advance one frame toward `target` if due, returning `true` if `current` changed and
+    /// should be pushed to the gamma backend.
+    pub fn step(&mut self) -> bool {
+        if !self.is_animating() {
+            return false;
+        }
+
+        let now = Instant::now();
+        if let Some(last_step) = self.last_step {
+            if now.duration_since(last_step) < FRAME_DURATION {
+                return false;
+            }
+        }
+        self.last_step = Some(now);
+
+        self.current = if self.current == UNSET {
+            self.target
+        } else if self.current < self.target {
+            self.current + 1
+        } else {
+            self.current - 1
+        };
+
+        true
+    }
+
+    /// Milliseconds until the next animation frame is due, or `-1` (block indefinitely)
+    /// when there's nothing to animate.
+    pub fn poll_timeout(&self) -> libc::c_int {
+        if !self.is_animating() {
+            return -1;
+        }
+
+        match self.last_step {
+            None => 0,
+            Some(last_step) => {
+                let elapsed = Instant::now().duration_since(last_step);
+                FRAME_DURATION.saturating_sub(elapsed).as_millis() as libc::c_int
+            }
+        }
+    }
+}
+
+/// Combine two `libc::poll` timeouts (milliseconds, `-1` meaning infinite) into the
+/// smallest finite one, or `-1` if both are infinite.
+pub(crate) fn combine_timeout(a: libc::c_int, b: libc::c_int) -> libc::c_int {
+    match (a, b) {
+        (-1, -1) => -1,
+        (-1, x) | (x, -1) => x,
+        (x, y) => x.min(y),
+    }
+}
+
+/// Re-assertion backoff after a Mutter display-config signal: Mutter may fight us for gamma
+/// control for a short while after reconfiguring a display, so keep forcing a brightness
+/// refresh for a handful of short poll timeouts instead of going straight back to blocking
+/// indefinitely.
+struct Backoff {
+    timeout: libc::c_int,
+    remaining: u32,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Backoff {
+            timeout: -1,
+            remaining: 0,
+        }
+    }
+
+    /// Start (or restart) the backoff: re-assert every `timeout_ms`, on `times + 1`
+    /// consecutive poll timeouts.
+    fn trigger(&mut self, timeout_ms: libc::c_int, times: u32) {
+        self.timeout = timeout_ms;
+        self.remaining = times;
+    }
+
+    fn poll_timeout(&self) -> libc::c_int {
+        self.timeout
+    }
+
+    /// Called when `poll` times out; `true` if the caller should force a brightness refresh.
+    /// Counts down until the backoff is exhausted, then returns to blocking indefinitely.
+    fn on_poll_timeout(&mut self) -> bool {
+        if self.timeout == -1 {
+            return false;
+        }
+
+        if self.remaining == 0 {
+            self.timeout = -1;
+        } else {
+            self.remaining -= 1;
+        }
+
+        true
+    }
+}
+
+/// The run loop's single source of poll timeouts: folds the frame-timed brightness
+/// `Animator` and the Mutter re-assertion `Backoff` together, so `main` has one thing to
+/// ask for "how long can I block" and one thing to notify on timeout, instead of two
+/// parallel timeout mechanisms.
+pub struct RunLoop {
+    animator: Animator,
+    backoff: Backoff,
+}
+
+impl RunLoop {
+    pub fn new() -> Self {
+        RunLoop {
+            animator: Animator::new(),
+            backoff: Backoff::new(),
+        }
+    }
+
+    pub fn current(&self) -> u64 {
+        self.animator.current()
+    }
+
+    /// Set the brightness percentage to animate toward.
+    pub fn set_target(&mut self, target: u64) {
+        self.animator.set_target(target);
+    }
+
+    /// Force the next `step()` to apply immediately rather than ease in; see
+    /// `Animator::invalidate`.
+    pub fn invalidate(&mut self) {
+        self.animator.invalidate();
+    }
+
+    /// Advance one frame toward the target if due; see `Animator::step`.
+    pub fn step(&mut self) -> bool {
+        self.animator.step()
+    }
+
+    /// Start re-asserting gamma every `timeout_ms` for `times + 1` poll timeouts, e.g. after a
+    /// Mutter display-config signal that may fight us for gamma control for a short while.
+    pub fn trigger_backoff(&mut self, timeout_ms: libc::c_int, times: u32) {
+        self.backoff.trigger(timeout_ms, times);
+    }
+
+    /// Smallest of the animator's next-frame timeout and the backoff's re-assertion
+    /// timeout, for `libc::poll` — or `-1` (block indefinitely) if neither is pending.
+    pub fn poll_timeout(&self) -> libc::c_int {
+        combine_timeout(self.animator.poll_timeout(), self.backoff.poll_timeout())
+    }
+
+    /// Call when `poll` returns `0` (timed out): invalidates the animator if the backoff is
+    /// still active, so the next frame re-asserts gamma even though nothing else changed.
+    pub fn on_poll_timeout(&mut self) {
+        if self.backoff.on_poll_timeout() {
+            self.animator.invalidate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animator_applies_first_step_immediately() {
+        let mut animator = Animator::new();
+        animator.set_target(50);
+        assert!(animator.step());
+        assert_eq!(animator.current(), 50);
+        assert!(!animator.step());
+    }
+
+    #[test]
+    fn animator_eases_toward_target_one_step_per_frame() {
+        let mut animator = Animator::new();
+        animator.set_target(10);
+        assert!(animator.step());
+        assert_eq!(animator.current(), 10);
+
+        animator.set_target(13);
+        assert!(animator.is_animating());
+        // Too soon for another frame: no-op until FRAME_DURATION has elapsed.
+        assert!(!animator.step());
+        assert_eq!(animator.current(), 10);
+
+        // Once a frame has elapsed, it moves a single step rather than jumping to 13.
+        std::thread::sleep(FRAME_DURATION);
+        assert!(animator.step());
+        assert_eq!(animator.current(), 11);
+    }
+
+    #[test]
+    fn animator_poll_timeout_is_infinite_when_idle() {
+        let animator = Animator::new();
+        assert_eq!(animator.poll_timeout(), -1);
+    }
+
+    #[test]
+    fn combine_timeout_picks_the_smaller_finite_value() {
+        assert_eq!(combine_timeout(-1, -1), -1);
+        assert_eq!(combine_timeout(-1, 50), 50);
+        assert_eq!(combine_timeout(50, -1), 50);
+        assert_eq!(combine_timeout(100, 20), 20);
+    }
+
+    #[test]
+    fn backoff_fires_for_one_more_than_the_configured_times_then_stops() {
+        let mut backoff = Backoff::new();
+        assert_eq!(backoff.poll_timeout(), -1);
+        assert!(!backoff.on_poll_timeout());
+
+        backoff.trigger(100, 2);
+        assert_eq!(backoff.poll_timeout(), 100);
+        assert!(backoff.on_poll_timeout());
+        assert!(backoff.on_poll_timeout());
+        assert!(backoff.on_poll_timeout());
+        assert_eq!(backoff.poll_timeout(), -1);
+        assert!(!backoff.on_poll_timeout());
+    }
+
+    #[test]
+    fn run_loop_invalidates_on_backoff_timeout() {
+        let mut run_loop = RunLoop::new();
+        run_loop.set_target(80);
+        assert!(run_loop.step());
+        assert_eq!(run_loop.current(), 80);
+
+        run_loop.trigger_backoff(50, 1);
+        assert_eq!(run_loop.poll_timeout(), 50);
+
+        run_loop.on_poll_timeout();
+        // Invalidated: the next step re-applies the target immediately rather than easing.
+        assert!(run_loop.step());
+        assert_eq!(run_loop.current(), 80);
+    }
+}
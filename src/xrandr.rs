@@ -0,0 +1,356 @@
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::ptr::NonNull;
+use std::slice;
+use std::str;
+
+use log::{error, trace};
+use x11::{xlib, xrandr};
+
+use crate::gamma::GammaBackend;
+
+pub struct ScreenNumber(libc::c_int);
+
+pub struct RootWindow(libc::c_ulong);
+
+pub struct Crtc(xrandr::RRCrtc);
+
+pub struct CrtcGamma(NonNull<xrandr::XRRCrtcGamma>);
+
+impl CrtcGamma {
+    pub fn size(&self) -> libc::c_int {
+        unsafe {
+            self.0.as_ref().size
+        }
+    }
+
+    pub fn channels(&mut self) -> (&mut [libc::c_ushort], &mut [libc::c_ushort], &mut [libc::c_ushort]) {
+        unsafe {
+            (
+                slice::from_raw_parts_mut(
+                    self.0.as_ref().red,
+                    self.0.as_ref().size as usize
+                ),
+                slice::from_raw_parts_mut(
+                    self.0.as_ref().green,
+                    self.0.as_ref().size as usize
+                ),
+                slice::from_raw_parts_mut(
+                    self.0.as_ref().blue,
+                    self.0.as_ref().size as usize
+                ),
+            )
+        }
+    }
+}
+
+impl Drop for CrtcGamma {
+    fn drop(&mut self) {
+        unsafe {
+            xrandr::XRRFreeGamma(self.0.as_ptr());
+        }
+    }
+}
+
+pub struct OutputInfo(NonNull<xrandr::XRROutputInfo>);
+
+impl OutputInfo {
+    pub fn name(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self.0.as_ref().name as *const u8,
+                self.0.as_ref().nameLen as usize
+            )
+        }
+    }
+
+    pub fn crtc(&self) -> Option<Crtc> {
+        let crtc = unsafe {
+            self.0.as_ref().crtc
+        };
+        if crtc == 0 {
+            None
+        } else {
+            Some(Crtc(crtc))
+        }
+    }
+}
+
+impl Drop for OutputInfo {
+    fn drop(&mut self) {
+        unsafe {
+            xrandr::XRRFreeOutputInfo(self.0.as_ptr());
+        }
+    }
+}
+
+pub struct Output(xrandr::RROutput);
+
+pub struct OutputsIter<'a> {
+    items: &'a [xrandr::RROutput],
+    i: usize,
+}
+
+impl<'a> Iterator for OutputsIter<'a> {
+    type Item = Output;
+    fn next(&mut self) -> Option<Output> {
+        if let Some(item) = self.items.get(self.i) {
+            self.i += 1;
+            Some(Output(*item))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct ScreenResources(NonNull<xrandr::XRRScreenResources>);
+
+impl ScreenResources {
+    pub fn outputs(&self) -> OutputsIter {
+        let items = unsafe {
+            slice::from_raw_parts(
+                self.0.as_ref().outputs,
+                self.0.as_ref().noutput as usize
+            )
+        };
+        OutputsIter {
+            items,
+            i: 0,
+        }
+    }
+}
+
+impl Drop for ScreenResources {
+    fn drop(&mut self) {
+        unsafe {
+            xrandr::XRRFreeScreenResources(self.0.as_ptr());
+        }
+    }
+}
+
+pub struct Display(NonNull<xlib::Display>);
+
+impl Display {
+    pub fn new() -> Option<Self> {
+        NonNull::new(unsafe {
+            xlib::XOpenDisplay(ptr::null())
+        }).map(Self)
+    }
+
+    pub fn default_screen_number(&self) -> ScreenNumber {
+        ScreenNumber(unsafe {
+            xlib::XDefaultScreen(self.0.as_ptr())
+        })
+    }
+
+    pub fn root_window(&self, screen_number: &ScreenNumber) -> RootWindow {
+        RootWindow(unsafe {
+            xlib::XRootWindow(self.0.as_ptr(), screen_number.0)
+        })
+    }
+
+    pub fn get_screen_resources(&self, root_window: &RootWindow, current: bool) -> Option<ScreenResources> {
+        NonNull::new(unsafe {
+            if current {
+                xrandr::XRRGetScreenResourcesCurrent(self.0.as_ptr(), root_window.0)
+            } else {
+                xrandr::XRRGetScreenResources(self.0.as_ptr(), root_window.0)
+            }
+        }).map(ScreenResources)
+    }
+
+    pub fn get_output_info(&self, resources: &ScreenResources, output: &Output) -> Option<OutputInfo> {
+        NonNull::new(unsafe {
+            xrandr::XRRGetOutputInfo(self.0.as_ptr(), resources.0.as_ptr(), output.0)
+        }).map(OutputInfo)
+    }
+
+    pub fn get_crtc_gamma(&self, crtc: &Crtc) -> Option<CrtcGamma> {
+        NonNull::new(unsafe {
+            xrandr::XRRGetCrtcGamma(self.0.as_ptr(), crtc.0)
+        }).map(CrtcGamma)
+    }
+
+    pub fn set_crtc_gamma(&mut self, crtc: &Crtc, gamma: &CrtcGamma) {
+        unsafe {
+            xrandr::XRRSetCrtcGamma(self.0.as_ptr(), crtc.0, gamma.0.as_ptr());
+        }
+    }
+
+    pub fn select_input(&mut self, root_window: &RootWindow, mask: libc::c_int) {
+        unsafe {
+            xrandr::XRRSelectInput(self.0.as_ptr(), root_window.0, mask);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        unsafe {
+            xlib::XFlush(self.0.as_ptr());
+        }
+    }
+
+    pub fn pending(&self) -> libc::c_int {
+        unsafe {
+            xlib::XPending(self.0.as_ptr())
+        }
+    }
+}
+
+impl AsRawFd for Display {
+    fn as_raw_fd(&self) -> RawFd {
+        unsafe {
+            xlib::XConnectionNumber(self.0.as_ptr())
+        }
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        unsafe {
+            xlib::XCloseDisplay(self.0.as_ptr());
+        }
+    }
+}
+
+/// Gamma backend that drives an X11 CRTC's gamma ramp through Xrandr. This is the only
+/// backend that can work under Xorg, and the only one that needs to pump its own event
+/// queue to notice output changes.
+pub struct XrandrBackend {
+    display: Display,
+    root_window: RootWindow,
+    xrr_event_base: libc::c_int,
+}
+
+impl XrandrBackend {
+    pub fn new() -> Option<Self> {
+        let mut display = Display::new()?;
+        trace!("display {:p}", display.0.as_ptr());
+
+        let mut xrr_event_base = 0;
+        let mut xrr_error_base = 0;
+        if unsafe { xrandr::XRRQueryExtension(display.0.as_ptr(), &mut xrr_event_base, &mut xrr_error_base) } == 0 {
+            error!("Xrandr extension not found");
+            return None;
+        }
+        trace!("xrr_event_base {:#x}, xrr_error_base {:#x}", xrr_event_base, xrr_error_base);
+
+        let screen_number = display.default_screen_number();
+        trace!("screen_number {:#x}", screen_number.0);
+
+        let root_window = display.root_window(&screen_number);
+        trace!("root_window {:#x}", root_window.0);
+
+        display.select_input(&root_window, xrandr::RROutputChangeNotifyMask);
+
+        Some(XrandrBackend {
+            display,
+            root_window,
+            xrr_event_base,
+        })
+    }
+
+    fn find_crtc(&self, connector: &str) -> Option<Crtc> {
+        let resources = self.display.get_screen_resources(&self.root_window, true)?;
+        trace!("resources {:p}", resources.0.as_ptr());
+
+        for output in resources.outputs() {
+            trace!("output {:#x}", output.0);
+            if let Some(info) = self.display.get_output_info(&resources, &output) {
+                trace!("info {:p}", info.0.as_ptr());
+                if let Ok(name) = str::from_utf8(info.name()) {
+                    trace!("name {}", name);
+                    if name.starts_with(connector) {
+                        trace!("matches {}", connector);
+                        return info.crtc();
+                    }
+                }
+            } else {
+                error!("failed to get X output info");
+            }
+        }
+
+        None
+    }
+}
+
+impl GammaBackend for XrandrBackend {
+    fn gamma_size(&self, connector: &str) -> Option<usize> {
+        let crtc = self.find_crtc(connector)?;
+        trace!("crtc {:#x}", crtc.0);
+        self.display.get_crtc_gamma(&crtc).map(|gamma| gamma.size() as usize)
+    }
+
+    fn set_gamma(&mut self, connector: &str, red: &[u16], green: &[u16], blue: &[u16]) -> bool {
+        let crtc = match self.find_crtc(connector) {
+            Some(crtc) => crtc,
+            None => return false,
+        };
+
+        let mut gamma = match self.display.get_crtc_gamma(&crtc) {
+            Some(gamma) => gamma,
+            None => {
+                error!("failed to get X gamma info");
+                return false;
+            }
+        };
+        trace!("gamma {:p}", gamma.0.as_ptr());
+
+        // The CRTC's gamma ramp size was fetched independently by `gamma_size()` and could
+        // have changed since (e.g. the hotplug/reconfiguration event this daemon watches
+        // for); bail out instead of panicking on a `copy_from_slice` length mismatch, and
+        // let the next run-loop iteration retry against a freshly-sized ramp.
+        if gamma.size() as usize != red.len() {
+            error!(
+                "X gamma ramp size changed from {} to {} entries since sizing, skipping this update",
+                red.len(), gamma.size()
+            );
+            return false;
+        }
+
+        {
+            let (dst_red, dst_green, dst_blue) = gamma.channels();
+            dst_red.copy_from_slice(red);
+            dst_green.copy_from_slice(green);
+            dst_blue.copy_from_slice(blue);
+        }
+
+        trace!("set gamma");
+        self.display.set_crtc_gamma(&crtc, &gamma);
+
+        trace!("flush");
+        self.display.flush();
+
+        true
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        Some(self.display.as_raw_fd())
+    }
+
+    fn process_events(&mut self) -> bool {
+        let mut changed = false;
+        while self.display.pending() > 0 {
+            unsafe {
+                let mut event = mem::zeroed::<xlib::XEvent>();
+                xlib::XNextEvent(self.display.0.as_ptr(), &mut event);
+                trace!("event {:#x}", event.type_);
+                if event.type_ >= self.xrr_event_base {
+                    let xrr_event_type = event.type_ - self.xrr_event_base;
+                    trace!("xrr_event {:#x}", xrr_event_type);
+                    if xrr_event_type == xrandr::RRNotify {
+                        let notify_event: &xrandr::XRRNotifyEvent = event.as_ref();
+                        trace!("notify_event {:?}", notify_event);
+                        if notify_event.subtype == xrandr::RRNotify_OutputChange {
+                            let output_change_event: &xrandr::XRROutputChangeNotifyEvent = event.as_ref();
+                            trace!("output_change_event {:?}", output_change_event);
+                        }
+
+                        changed = true;
+                    }
+                }
+            }
+        }
+        changed
+    }
+}